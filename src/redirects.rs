@@ -0,0 +1,279 @@
+// SPDX-FileCopyrightText: 2022 Sascha Brawer <sascha@brawer.ch>
+// SPDX-License-Identifier: MIT
+//
+// Second pass, run after `process()`: ingest the per-wiki `page` and
+// `redirect` SQL table dumps and add a case-folded key for every
+// redirect's source title, pointing at the same Wikidata ID as the
+// redirect's target. This lets a lookup of a title that was since
+// renamed, or that is a redirect, still resolve -- not just the current
+// titles that `process` indexes from the Wikidata dump.
+//
+// Dumps are the standard MediaWiki `<wiki>-page.sql.gz` and
+// `<wiki>-redirect.sql.gz` table dumps, one subdirectory per wiki, e.g.
+// "dewiki/dewiki-page.sql.gz" and "dewiki/dewiki-redirect.sql.gz".
+
+use crate::codec::{self, InternedTags};
+use crate::{fold_title, split_wiki_key};
+use flate2::read::GzDecoder;
+use lmdb::Transaction;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+const MAIN_NAMESPACE: i64 = 0;
+
+/// Adds redirect-source titles to `db`, for every wiki that has both a
+/// `page` and a `redirect` dump under `dumps_dir`. A redirect never
+/// overwrites an existing key, so a stale redirect can't clobber a real
+/// current title.
+pub fn process_redirects(
+    dumps_dir: &Path,
+    env: &lmdb::Environment,
+    db: &lmdb::Database,
+    interned_db: &lmdb::Database,
+) -> Result<(), Box<dyn Error>> {
+    if !dumps_dir.is_dir() {
+        println!(
+            "redirect dumps directory {} not found, skipping redirects",
+            dumps_dir.display()
+        );
+        return Ok(());
+    }
+
+    let mut txn = env.begin_rw_txn()?;
+    let tags = InternedTags::load(&txn, *interned_db)?;
+    for entry in std::fs::read_dir(dumps_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let wiki = entry.file_name().to_string_lossy().into_owned();
+        let page_path = entry.path().join(format!("{wiki}-page.sql.gz"));
+        let redirect_path = entry.path().join(format!("{wiki}-redirect.sql.gz"));
+        if !page_path.exists() || !redirect_path.exists() {
+            continue;
+        }
+        let Some((lang, site)) = split_wiki_key(&wiki) else {
+            continue;
+        };
+        let (Some(&lang_id), Some(&site_id)) = (tags.lang_ids.get(&lang), tags.site_ids.get(&site))
+        else {
+            continue; // this (lang, site) never occurred in the Wikidata dump
+        };
+        process_wiki_redirects(
+            &page_path,
+            &redirect_path,
+            &lang,
+            lang_id,
+            site_id,
+            &mut txn,
+            db,
+        )?;
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+fn process_wiki_redirects(
+    page_path: &Path,
+    redirect_path: &Path,
+    lang: &str,
+    lang_id: u16,
+    site_id: u8,
+    txn: &mut lmdb::RwTransaction,
+    db: &lmdb::Database,
+) -> Result<(), Box<dyn Error>> {
+    // The page table still has to be fully materialized: the redirect
+    // table only carries page ids, so we need all titles available
+    // before making a single pass over the redirects. `iter_sql_rows`
+    // itself is streaming, so this is one title string per page, not
+    // also a parsed-rows buffer held alongside it.
+    let mut page_titles: HashMap<u64, String> = HashMap::new();
+    for fields in iter_sql_rows(page_path)? {
+        if fields.len() < 3 {
+            continue;
+        }
+        let (Ok(page_id), Ok(namespace)) = (fields[0].parse::<u64>(), fields[1].parse::<i64>())
+        else {
+            continue;
+        };
+        if namespace != MAIN_NAMESPACE {
+            continue;
+        }
+        page_titles.insert(page_id, fields[2].clone());
+    }
+
+    for fields in iter_sql_rows(redirect_path)? {
+        if fields.len() < 3 {
+            continue;
+        }
+        let (Ok(rd_from), Ok(rd_namespace)) = (fields[0].parse::<u64>(), fields[1].parse::<i64>())
+        else {
+            continue;
+        };
+        if rd_namespace != MAIN_NAMESPACE {
+            continue;
+        }
+        let rd_title = &fields[2];
+        let Some(source_title) = page_titles.get(&rd_from) else {
+            continue;
+        };
+
+        let target_key = codec::encode(lang_id, site_id, &fold_title(lang, rd_title));
+        let qid = match txn.get(*db, &target_key) {
+            Ok(qid) => qid.to_vec(),
+            Err(lmdb::Error::NotFound) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let source_key = codec::encode(lang_id, site_id, &fold_title(lang, source_title));
+        match txn.put(*db, &source_key, &qid, lmdb::WriteFlags::NO_OVERWRITE) {
+            Ok(()) | Err(lmdb::Error::KeyExist) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Parses the `INSERT INTO ... VALUES (...), (...), ...;` statements of a
+/// gzipped MediaWiki SQL table dump, yielding each row as its raw field
+/// strings (quotes stripped, integers left as decimal text).
+///
+/// Reads and parses one dump line at a time rather than collecting the
+/// whole (potentially multi-GB, for a large wiki) table into memory
+/// first; a dump line is itself one `INSERT` statement's worth of
+/// tuples, so memory use is bounded by that, not by the whole table.
+fn iter_sql_rows(path: &Path) -> Result<impl Iterator<Item = Vec<String>>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(GzDecoder::new(file));
+    Ok(SqlRows {
+        lines: reader.lines(),
+        pending: Vec::new().into_iter(),
+    })
+}
+
+/// This is a small ad hoc parser for the specific dumps we consume here,
+/// not a general SQL parser.
+struct SqlRows<R> {
+    lines: std::io::Lines<R>,
+    pending: std::vec::IntoIter<Vec<String>>,
+}
+
+impl<R: BufRead> Iterator for SqlRows<R> {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Vec<String>> {
+        loop {
+            if let Some(row) = self.pending.next() {
+                return Some(row);
+            }
+            let line = self.lines.next()?.ok()?;
+            if !line.starts_with("INSERT INTO") {
+                continue;
+            }
+            self.pending = parse_insert_tuples(&line).into_iter();
+        }
+    }
+}
+
+/// Parses one `INSERT INTO ... VALUES (...), (...), ...;` line into its
+/// tuples of raw field strings. Fields are accumulated as raw bytes
+/// (MediaWiki dumps are UTF-8, and a naive byte-by-byte `as char` cast
+/// mangles any non-ASCII title) and decoded once a field closes, so a
+/// multi-byte UTF-8 sequence never gets split across two `char`s. The
+/// handful of backslash escapes mysqldump emits inside strings
+/// (`\n`, `\r`, `\0`, `\t`, plus `\\` and `\'` passed through as-is) are
+/// translated to their actual byte value rather than left as the raw
+/// escaped byte.
+fn parse_insert_tuples(sql: &str) -> Vec<Vec<String>> {
+    let bytes = sql.as_bytes();
+    let mut tuples = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        while i < bytes.len() && bytes[i] != b'(' {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            break;
+        }
+        i += 1;
+        let mut fields = Vec::new();
+        let mut field = Vec::new();
+        let mut in_string = false;
+        while i < bytes.len() {
+            let b = bytes[i];
+            if in_string {
+                if b == b'\\' && i + 1 < bytes.len() {
+                    field.push(match bytes[i + 1] {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'0' => 0,
+                        other => other, // \\ -> \, \' -> ', anything else passed through
+                    });
+                    i += 2;
+                    continue;
+                }
+                if b == b'\'' {
+                    in_string = false;
+                    i += 1;
+                    continue;
+                }
+                field.push(b);
+                i += 1;
+            } else {
+                match b {
+                    b'\'' => {
+                        in_string = true;
+                        i += 1;
+                    }
+                    b',' => {
+                        fields.push(take_field(&mut field));
+                        i += 1;
+                    }
+                    b')' => {
+                        fields.push(take_field(&mut field));
+                        i += 1;
+                        break;
+                    }
+                    _ => {
+                        field.push(b);
+                        i += 1;
+                    }
+                }
+            }
+        }
+        tuples.push(fields);
+    }
+    tuples
+}
+
+fn take_field(field: &mut Vec<u8>) -> String {
+    String::from_utf8_lossy(&std::mem::take(field)).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_multiple_tuples_with_escapes_and_non_ascii() {
+        let sql = r"INSERT INTO `page` VALUES (1,0,'Zürich',0),(2,0,'It\'s \'quoted\'',0),(3,0,'日本',0);";
+        let tuples = parse_insert_tuples(sql);
+        assert_eq!(
+            tuples,
+            vec![
+                vec!["1", "0", "Zürich", "0"],
+                vec!["2", "0", "It's 'quoted'", "0"],
+                vec!["3", "0", "日本", "0"],
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_backslash_escapes() {
+        let sql = r"INSERT INTO `t` VALUES (1,'a\nb\rc\0d');";
+        assert_eq!(parse_insert_tuples(sql), vec![vec!["1", "a\nb\rc\0d"]]);
+    }
+}
@@ -0,0 +1,184 @@
+// SPDX-FileCopyrightText: 2022 Sascha Brawer <sascha@brawer.ch>
+// SPDX-License-Identifier: MIT
+//
+// Read-side lookup API for the (language, site, title) --> "Q1234" mapping
+// built by `process()` in main.rs. `Resolver` opens the zstd-compressed
+// LMDB database produced there and reuses the same title case-folding
+// and key encoding logic, so a lookup finds exactly the keys that were
+// written.
+
+use crate::codec::{self, InternedTags};
+use crate::{canonicalize_lang, fold_title};
+use lmdb::Transaction;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Looks up Wikidata IDs for Wikimedia page titles.
+///
+/// Opens a `sitelinks-*.mdb.zst` file as produced by `process()`,
+/// decompressing it to a temporary LMDB environment on disk.
+pub struct Resolver {
+    env: lmdb::Environment,
+    db: lmdb::Database,
+    tags: InternedTags,
+    mdb_path: PathBuf,
+}
+
+impl Resolver {
+    /// Decompresses `zst_path` to a temporary LMDB environment and opens it
+    /// for reading.
+    pub fn open(zst_path: &Path) -> Result<Resolver, Box<dyn Error>> {
+        static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let mdb_path = std::env::temp_dir().join(format!(
+            "qsitelinks-resolver-{}-{}.mdb",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let in_file = File::open(zst_path)?;
+        let out_file = File::create(&mdb_path)?;
+        zstd::stream::copy_decode(in_file, out_file)?;
+
+        let mut env_flags = lmdb::EnvironmentFlags::empty();
+        env_flags.set(lmdb::EnvironmentFlags::NO_SUB_DIR, true);
+        env_flags.set(lmdb::EnvironmentFlags::READ_ONLY, true);
+        let env = lmdb::Environment::new()
+            .set_flags(env_flags)
+            .set_max_dbs(2)
+            .open(&mdb_path)?;
+        let db = env.open_db(None)?;
+        let interned_db = env.open_db(Some(codec::INTERNED_DB_NAME))?;
+        let txn = env.begin_ro_txn()?;
+        let tags = InternedTags::load(&txn, interned_db)?;
+        txn.commit()?;
+        Ok(Resolver {
+            env,
+            db,
+            tags,
+            mdb_path,
+        })
+    }
+
+    /// Looks up the Wikidata ID for a title on a given language/site.
+    ///
+    /// Besides a direct hit, this tries the locale fallback chain of
+    /// UTS #35 ("pt-BR" -> "pt" -> "und"), dropping the most specific
+    /// trailing subtag of `lang` on each step while `site` is held
+    /// fixed, and returns the Wikidata ID stored under the first key found.
+    pub fn resolve(&self, lang: &str, site: &str, title: &str) -> Option<String> {
+        let site_id = *self.tags.site_ids.get(site)?;
+        let mut lang = canonicalize_lang(lang);
+        loop {
+            if let Some(&lang_id) = self.tags.lang_ids.get(&lang) {
+                let folded_title = fold_title(&lang, title);
+                let key = codec::encode(lang_id, site_id, &folded_title);
+                if let Some(value) = self.get(&key) {
+                    return Some(value);
+                }
+            }
+            if lang == "und" {
+                return None;
+            }
+            match lang.rfind('-') {
+                Some(pos) => lang.truncate(pos),
+                None => lang = String::from("und"),
+            }
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Option<String> {
+        let txn = self.env.begin_ro_txn().ok()?;
+        let value = txn.get(self.db, &key).ok()?;
+        Some(String::from_utf8_lossy(value).into_owned())
+    }
+}
+
+impl Drop for Resolver {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.mdb_path);
+        _ = std::fs::remove_file(format!("{}-lock", self.mdb_path.display()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Interner;
+
+    /// Builds a `sitelinks-*.mdb.zst` file containing `entries`
+    /// (lang, site, title, qid) the same way `process()` would, and
+    /// returns its path.
+    fn build_test_db(name: &str, entries: &[(&str, &str, &str, &str)]) -> PathBuf {
+        let mdb_path = std::env::temp_dir().join(format!("qsitelinks-test-{name}.mdb"));
+        let zst_path = std::env::temp_dir().join(format!("qsitelinks-test-{name}.mdb.zst"));
+        _ = std::fs::remove_file(&mdb_path);
+        _ = std::fs::remove_file(&zst_path);
+
+        let mut env_flags = lmdb::EnvironmentFlags::empty();
+        env_flags.set(lmdb::EnvironmentFlags::NO_SUB_DIR, true);
+        let env = lmdb::Environment::new()
+            .set_flags(env_flags)
+            .set_map_size(16 * 1024 * 1024)
+            .set_max_dbs(2)
+            .open(&mdb_path)
+            .unwrap();
+        let db = env.create_db(None, lmdb::DatabaseFlags::empty()).unwrap();
+        let interned_db = env
+            .create_db(Some(codec::INTERNED_DB_NAME), lmdb::DatabaseFlags::empty())
+            .unwrap();
+
+        let mut interner = Interner::new();
+        let mut txn = env.begin_rw_txn().unwrap();
+        for (lang, site, title, qid) in entries {
+            let lang = canonicalize_lang(lang);
+            let folded_title = fold_title(&lang, title);
+            let lang_id = interner.intern_lang(&lang);
+            let site_id = interner.intern_site(site);
+            let key = codec::encode(lang_id, site_id, &folded_title);
+            txn.put(db, &key, qid, lmdb::WriteFlags::empty()).unwrap();
+        }
+        interner.write(&mut txn, interned_db).unwrap();
+        txn.commit().unwrap();
+        drop(env);
+
+        let in_file = File::open(&mdb_path).unwrap();
+        let out_file = File::create(&zst_path).unwrap();
+        zstd::stream::copy_encode(in_file, out_file, 1).unwrap();
+        _ = std::fs::remove_file(&mdb_path);
+        _ = std::fs::remove_file(format!("{}-lock", mdb_path.display()));
+        zst_path
+    }
+
+    #[test]
+    fn resolve_direct_hit() {
+        let zst_path = build_test_db("direct-hit", &[("de", "", "Berlin", "Q64")]);
+        let resolver = Resolver::open(&zst_path).unwrap();
+        assert_eq!(
+            resolver.resolve("de", "", "Berlin"),
+            Some("Q64".to_string())
+        );
+        _ = std::fs::remove_file(&zst_path);
+    }
+
+    #[test]
+    fn resolve_falls_back_through_locale_chain() {
+        let zst_path = build_test_db(
+            "locale-fallback",
+            &[("pt", "", "Title", "Q1"), ("und", "", "Fallback", "Q2")],
+        );
+        let resolver = Resolver::open(&zst_path).unwrap();
+        // "pt-BR" isn't in the database, so this should fall back to "pt".
+        assert_eq!(
+            resolver.resolve("pt-BR", "", "Title"),
+            Some("Q1".to_string())
+        );
+        // Neither "xx-YY" nor "xx" is in the database, so this should fall
+        // all the way back to "und".
+        assert_eq!(
+            resolver.resolve("xx-YY", "", "Fallback"),
+            Some("Q2".to_string())
+        );
+        assert_eq!(resolver.resolve("xx-YY", "", "Missing"), None);
+        _ = std::fs::remove_file(&zst_path);
+    }
+}
@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: 2022 Sascha Brawer <sascha@brawer.ch>
+// SPDX-License-Identifier: MIT
+//
+// Reverse index from a Wikidata ID to every sitelink title about it,
+// across all languages and projects: given "Q1234", enumerate every
+// Wikipedia/sister-project page about it, e.g.
+// ["en:Title_A", "de:Titel_B", "en.wikisource:Foo"] -- the cross-language
+// "same content, many locales" grouping. Guarded behind `--reverse-index`
+// since it roughly doubles the size of the output database.
+
+use std::error::Error;
+
+pub const REVERSE_DB_NAME: &str = "reverse";
+
+/// Formats one sitelink as "lang.wikisite:title", or "lang:title" when
+/// `site` is empty (the ordinary Wikipedia case). This is a
+/// human-readable entry in the reverse index, not case-folded like the
+/// forward lookup keys in codec.rs.
+fn format_sitelink(lang: &str, site: &str, title: &str) -> String {
+    let mut s = String::with_capacity(lang.len() + 1 + site.len() + 1 + title.len());
+    s.push_str(lang);
+    if !site.is_empty() {
+        s.push_str(".wiki");
+        s.push_str(site);
+    }
+    s.push(':');
+    s.push_str(title);
+    s
+}
+
+/// Serializes the sitelinks of one entity as a length-prefixed
+/// concatenation of `format_sitelink` strings.
+pub fn encode(sitelinks: &[(String, String, String)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for (lang, site, title) in sitelinks {
+        let entry = format_sitelink(lang, site, title);
+        buf.extend_from_slice(&(entry.len() as u16).to_le_bytes());
+        buf.extend_from_slice(entry.as_bytes());
+    }
+    buf
+}
+
+/// Decodes a reverse-index value back into its "lang.wikisite:title"
+/// strings.
+pub fn decode(value: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + 2 <= value.len() {
+        let len = u16::from_le_bytes([value[pos], value[pos + 1]]) as usize;
+        pos += 2;
+        if pos + len > value.len() {
+            break;
+        }
+        entries.push(std::str::from_utf8(&value[pos..pos + len])?.to_string());
+        pos += len;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_sitelink_omits_site_when_empty() {
+        assert_eq!(format_sitelink("de", "", "Berlin"), "de:Berlin");
+        assert_eq!(
+            format_sitelink("de", "source", "Berlin"),
+            "de.wikisource:Berlin"
+        );
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let sitelinks = vec![
+            ("en".to_string(), "".to_string(), "Berlin".to_string()),
+            (
+                "de".to_string(),
+                "source".to_string(),
+                "Büchlein".to_string(),
+            ),
+        ];
+        let value = encode(&sitelinks);
+        assert_eq!(
+            decode(&value).unwrap(),
+            vec![
+                "en:Berlin".to_string(),
+                "de.wikisource:Büchlein".to_string()
+            ],
+        );
+    }
+
+    #[test]
+    fn decode_of_empty_value_is_empty() {
+        assert_eq!(decode(&[]).unwrap(), Vec::<String>::new());
+    }
+}